@@ -0,0 +1,258 @@
+use std::future::{Ready, ready};
+use std::rc::Rc;
+
+use actix_web::body::EitherBody;
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform, forward_ready};
+use actix_web::{Error, HttpMessage, HttpResponse, web};
+use futures_util::future::LocalBoxFuture;
+use tracing::error;
+
+use crate::AppState;
+use crate::store::Permissions;
+
+/// Marker inserted into request extensions once a DB-issued API key has authorized the
+/// request, so the handler can skip its own (legacy) auth check.
+pub struct ApiKeyAuthorized;
+
+/// Middleware that resolves `X-Auth-Token` against the `api_keys` table and short-circuits
+/// to a handler-level auth check when no key matches, so static tokens and macaroons keep
+/// working alongside per-key permissions.
+pub struct ApiKeyAuth {
+    required: Permissions,
+}
+
+impl ApiKeyAuth {
+    pub fn new(required: Permissions) -> Self {
+        Self { required }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for ApiKeyAuth
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = ApiKeyAuthMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(ApiKeyAuthMiddleware {
+            service: Rc::new(service),
+            required: self.required,
+        }))
+    }
+}
+
+pub struct ApiKeyAuthMiddleware<S> {
+    service: Rc<S>,
+    required: Permissions,
+}
+
+impl<S, B> Service<ServiceRequest> for ApiKeyAuthMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        let required = self.required;
+
+        Box::pin(async move {
+            let token = req
+                .headers()
+                .get("X-Auth-Token")
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_owned);
+
+            if let Some(token) = token {
+                if let Some(state) = req.app_data::<web::Data<AppState>>() {
+                    match state.db.resolve_key(&token).await {
+                        Ok(Some(key)) if key.is_expired() => {}
+                        Ok(Some(key)) if !key.permissions.contains(required) => {
+                            let resp =
+                                HttpResponse::Unauthorized().body("Insufficient permissions");
+                            return Ok(req.into_response(resp).map_into_right_body());
+                        }
+                        Ok(Some(_)) => {
+                            req.extensions_mut().insert(ApiKeyAuthorized);
+                        }
+                        Ok(None) => {}
+                        Err(e) => error!("failed to resolve api key: {}", e),
+                    }
+                }
+            }
+
+            let res = service.call(req).await?;
+            Ok(res.map_into_left_body())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use std::sync::Arc;
+
+    use actix_web::http::StatusCode;
+    use actix_web::{App, HttpRequest, test};
+    use arc_swap::ArcSwap;
+    use async_trait::async_trait;
+    use chrono::{Duration, Utc};
+
+    use super::*;
+    use crate::raster::RasterCache;
+    use crate::store::{ApiKeyRecord, ApiKeyStore, CounterRecord, CounterStore};
+
+    /// A `Store` that always resolves `X-Auth-Token` to the one key it was built with,
+    /// regardless of what token is presented. Good enough to drive the middleware in isolation.
+    struct MockStore {
+        key: ApiKeyRecord,
+    }
+
+    #[async_trait]
+    impl CounterStore for MockStore {
+        async fn increment(&self, _name: &str) -> Result<i64, sqlx::Error> {
+            unimplemented!("not exercised by the auth middleware tests")
+        }
+
+        async fn set(&self, _name: &str, _count: i64) -> Result<(), sqlx::Error> {
+            unimplemented!("not exercised by the auth middleware tests")
+        }
+
+        async fn get(&self, _name: &str) -> Result<i64, sqlx::Error> {
+            unimplemented!("not exercised by the auth middleware tests")
+        }
+
+        async fn list(&self) -> Result<Vec<CounterRecord>, sqlx::Error> {
+            unimplemented!("not exercised by the auth middleware tests")
+        }
+    }
+
+    #[async_trait]
+    impl ApiKeyStore for MockStore {
+        async fn create_key(
+            &self,
+            _description: &str,
+            _permissions: Permissions,
+            _expires_at: Option<chrono::DateTime<chrono::Utc>>,
+        ) -> Result<String, sqlx::Error> {
+            unimplemented!("not exercised by the auth middleware tests")
+        }
+
+        async fn resolve_key(&self, _token: &str) -> Result<Option<ApiKeyRecord>, sqlx::Error> {
+            Ok(Some(self.key.clone()))
+        }
+    }
+
+    fn state_with_key(key: ApiKeyRecord) -> web::Data<AppState> {
+        web::Data::new(AppState {
+            db: Arc::new(MockStore { key }),
+            themes: Arc::new(ArcSwap::from_pointee(HashMap::new())),
+            default_theme: "moebooru".to_string(),
+            api_auth_token: None,
+            macaroon_secret: None,
+            raster_cache: RasterCache::default(),
+        })
+    }
+
+    /// Stands in for a handler guarded by `authorize_set`: it only succeeds once the
+    /// middleware has already vouched for the request via the `ApiKeyAuthorized` marker.
+    async fn downstream(req: HttpRequest) -> HttpResponse {
+        if req.extensions().get::<ApiKeyAuthorized>().is_some() {
+            HttpResponse::Ok().finish()
+        } else {
+            HttpResponse::Unauthorized().finish()
+        }
+    }
+
+    #[actix_web::test]
+    async fn rejects_a_key_without_the_required_permission() {
+        let state = state_with_key(ApiKeyRecord {
+            description: "read-only".into(),
+            permissions: Permissions::RESET,
+            expires_at: None,
+        });
+
+        let app = test::init_service(
+            App::new()
+                .app_data(state)
+                .wrap(ApiKeyAuth::new(Permissions::SET))
+                .route("/", web::get().to(downstream)),
+        )
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri("/")
+            .insert_header(("X-Auth-Token", "irrelevant"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        let body = test::read_body(resp).await;
+        assert_eq!(body, "Insufficient permissions");
+    }
+
+    #[actix_web::test]
+    async fn rejects_an_expired_key() {
+        let state = state_with_key(ApiKeyRecord {
+            description: "expired".into(),
+            permissions: Permissions::SET,
+            expires_at: Some(Utc::now() - Duration::seconds(1)),
+        });
+
+        let app = test::init_service(
+            App::new()
+                .app_data(state)
+                .wrap(ApiKeyAuth::new(Permissions::SET))
+                .route("/", web::get().to(downstream)),
+        )
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri("/")
+            .insert_header(("X-Auth-Token", "irrelevant"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        // The middleware soft-passes an expired key (no `ApiKeyAuthorized` marker), leaving
+        // the downstream handler's own auth check to reject the request.
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_web::test]
+    async fn authorizes_a_valid_key_with_the_required_permission() {
+        let state = state_with_key(ApiKeyRecord {
+            description: "writer".into(),
+            permissions: Permissions::SET,
+            expires_at: None,
+        });
+
+        let app = test::init_service(
+            App::new()
+                .app_data(state)
+                .wrap(ApiKeyAuth::new(Permissions::SET))
+                .route("/", web::get().to(downstream)),
+        )
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri("/")
+            .insert_header(("X-Auth-Token", "irrelevant"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+}
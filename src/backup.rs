@@ -0,0 +1,26 @@
+use std::fs;
+use std::io;
+
+use crate::store::Store;
+
+/// Dumps every counter to `out` as a JSON array of `{"name", "num"}` records.
+pub async fn dump(db: &dyn Store, out: &str) -> io::Result<()> {
+    let records = db.list().await.map_err(io::Error::other)?;
+    let json = serde_json::to_string_pretty(&records).map_err(io::Error::other)?;
+    fs::write(out, json)
+}
+
+/// Imports counters from a JSON file at `path`, upserting each one.
+pub async fn import(db: &dyn Store, path: &str) -> io::Result<()> {
+    let json = fs::read_to_string(path)?;
+    let records: Vec<crate::store::CounterRecord> =
+        serde_json::from_str(&json).map_err(io::Error::other)?;
+
+    for record in &records {
+        db.set(&record.name, record.num)
+            .await
+            .map_err(io::Error::other)?;
+    }
+
+    Ok(())
+}
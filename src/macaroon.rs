@@ -0,0 +1,208 @@
+use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+fn hmac_sign(key: &[u8], message: &[u8]) -> Vec<u8> {
+    let mut mac = HmacSha256::new_from_slice(key).expect("hmac accepts any key length");
+    mac.update(message);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// A macaroon: an identifier plus a chain of first-party caveats, each folded into the
+/// HMAC signature so any tampering with the identifier or caveat list invalidates it.
+pub struct Macaroon {
+    identifier: String,
+    caveats: Vec<String>,
+    signature: Vec<u8>,
+}
+
+impl Macaroon {
+    /// Mints a macaroon by signing `identifier` with `root_secret`, then chaining each
+    /// caveat's signature off the previous one: `sig = HMAC(prev_sig, caveat)`.
+    pub fn mint(root_secret: &[u8], identifier: &str, caveats: Vec<String>) -> Self {
+        let mut signature = hmac_sign(root_secret, identifier.as_bytes());
+        for caveat in &caveats {
+            signature = hmac_sign(&signature, caveat.as_bytes());
+        }
+        Self {
+            identifier: identifier.to_string(),
+            caveats,
+            signature,
+        }
+    }
+
+    /// Encodes the macaroon for transport in the `X-Auth-Token` header.
+    pub fn encode(&self) -> String {
+        let mut lines = Vec::with_capacity(self.caveats.len() + 2);
+        lines.push(self.identifier.clone());
+        lines.extend(self.caveats.iter().cloned());
+        lines.push(BASE64.encode(&self.signature));
+        BASE64.encode(lines.join("\n"))
+    }
+
+    /// Decodes a macaroon from its transport encoding. This does not verify the
+    /// signature; call [`Macaroon::verify`] before trusting the identifier or caveats.
+    pub fn decode(token: &str) -> Option<Self> {
+        let plaintext = BASE64.decode(token).ok()?;
+        let plaintext = String::from_utf8(plaintext).ok()?;
+        let lines: Vec<&str> = plaintext.split('\n').collect();
+        if lines.len() < 2 {
+            return None;
+        }
+
+        let identifier = lines[0].to_string();
+        let signature = BASE64.decode(lines[lines.len() - 1]).ok()?;
+        let caveats = lines[1..lines.len() - 1]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+
+        Some(Self {
+            identifier,
+            caveats,
+            signature,
+        })
+    }
+
+    /// Recomputes the HMAC chain from `root_secret` and checks it matches the carried
+    /// signature using a constant-time comparison, then evaluates every caveat against `ctx`.
+    pub fn verify(&self, root_secret: &[u8], ctx: &VerifyContext) -> Result<(), String> {
+        let (last_key, last_message) = match self.caveats.split_last() {
+            Some((last, rest)) => {
+                let mut key = hmac_sign(root_secret, self.identifier.as_bytes());
+                for caveat in rest {
+                    key = hmac_sign(&key, caveat.as_bytes());
+                }
+                (key, last.as_bytes())
+            }
+            None => (root_secret.to_vec(), self.identifier.as_bytes()),
+        };
+
+        let mut mac =
+            HmacSha256::new_from_slice(&last_key).expect("hmac accepts any key length");
+        mac.update(last_message);
+        mac.verify_slice(&self.signature)
+            .map_err(|_| "invalid macaroon signature".to_string())?;
+
+        for caveat in &self.caveats {
+            check_caveat(caveat, ctx)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The request-time facts first-party caveats are checked against.
+pub struct VerifyContext<'a> {
+    pub name: &'a str,
+    pub now: DateTime<Utc>,
+}
+
+fn check_caveat(caveat: &str, ctx: &VerifyContext) -> Result<(), String> {
+    if let Some(name) = caveat.strip_prefix("name = ") {
+        return if name.trim() == ctx.name {
+            Ok(())
+        } else {
+            Err(format!("caveat not satisfied: {caveat}"))
+        };
+    }
+
+    if let Some(expires) = caveat.strip_prefix("expires < ") {
+        let expires = DateTime::parse_from_rfc3339(expires.trim())
+            .map_err(|e| format!("invalid expires caveat '{caveat}': {e}"))?;
+        return if ctx.now < expires {
+            Ok(())
+        } else {
+            Err(format!("token expired: {caveat}"))
+        };
+    }
+
+    Err(format!("unrecognized caveat: {caveat}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ctx(name: &str) -> VerifyContext<'_> {
+        VerifyContext {
+            name,
+            now: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn round_trips_through_encode_decode_and_verify() {
+        let secret = b"root secret";
+        let macaroon = Macaroon::mint(secret, "visits-blog", vec!["name = visits-blog".into()]);
+
+        let decoded = Macaroon::decode(&macaroon.encode()).expect("valid encoding");
+        assert!(decoded.verify(secret, &ctx("visits-blog")).is_ok());
+    }
+
+    #[test]
+    fn rejects_a_tampered_caveat() {
+        let secret = b"root secret";
+        let mut macaroon =
+            Macaroon::mint(secret, "visits-blog", vec!["name = visits-blog".into()]);
+        macaroon.caveats[0] = "name = other-counter".into();
+
+        assert!(macaroon.verify(secret, &ctx("other-counter")).is_err());
+    }
+
+    #[test]
+    fn rejects_reordered_caveats() {
+        let secret = b"root secret";
+        let mut macaroon = Macaroon::mint(
+            secret,
+            "visits-blog",
+            vec![
+                "name = visits-blog".into(),
+                "expires < 2999-01-01T00:00:00Z".into(),
+            ],
+        );
+        macaroon.caveats.swap(0, 1);
+
+        assert!(macaroon.verify(secret, &ctx("visits-blog")).is_err());
+    }
+
+    #[test]
+    fn rejects_the_wrong_root_secret() {
+        let macaroon = Macaroon::mint(
+            b"root secret",
+            "visits-blog",
+            vec!["name = visits-blog".into()],
+        );
+
+        assert!(macaroon.verify(b"wrong secret", &ctx("visits-blog")).is_err());
+    }
+
+    #[test]
+    fn rejects_an_expired_token() {
+        let secret = b"root secret";
+        let macaroon = Macaroon::mint(
+            secret,
+            "visits-blog",
+            vec!["expires < 2000-01-01T00:00:00Z".into()],
+        );
+
+        let err = macaroon
+            .verify(secret, &ctx("visits-blog"))
+            .expect_err("caveat should be expired");
+        assert!(err.contains("token expired"));
+    }
+
+    #[test]
+    fn rejects_a_scope_mismatch() {
+        let secret = b"root secret";
+        let macaroon = Macaroon::mint(secret, "visits-blog", vec!["name = visits-blog".into()]);
+
+        let err = macaroon
+            .verify(secret, &ctx("other-counter"))
+            .expect_err("caveat should not be satisfied");
+        assert!(err.contains("caveat not satisfied"));
+    }
+}
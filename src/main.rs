@@ -1,12 +1,24 @@
-use actix_web::{App, HttpRequest, HttpResponse, HttpServer, Responder, get, web};
-use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
-use clap::Parser;
-use sqlx::SqlitePool;
-use std::collections::{HashMap, HashSet};
-use std::path::Path;
+use actix_web::{App, HttpMessage, HttpRequest, HttpResponse, HttpServer, Responder, get, web};
+use arc_swap::ArcSwap;
+use clap::{Parser, Subcommand};
+use std::collections::HashSet;
 use std::sync::Arc;
 use tracing::{debug, error, info, warn};
 
+mod auth;
+mod backup;
+mod macaroon;
+mod raster;
+mod store;
+mod themes;
+mod tls;
+
+use auth::{ApiKeyAuth, ApiKeyAuthorized};
+use macaroon::{Macaroon, VerifyContext};
+use raster::{OutputFormat, RasterCache, negotiate_format, rasterize};
+use store::{DbBackend, Permissions, PostgresStore, SqliteStore, Store};
+use themes::{ThemeData, ThemeMap, load_themes, spawn_theme_watcher};
+
 static SVG_TEMPLATE: &str = include_str!("../assets/counter.svg");
 const IMG_WIDTH: u32 = 45;
 const IMG_HEIGHT: u32 = 100;
@@ -23,10 +35,14 @@ struct Args {
     #[arg(long, env("BIND_PORT"), default_value_t = 8080)]
     port: u16,
 
-    /// Path to SQLite database
+    /// Database connection string (SQLite path or `sqlite://`/`postgres://` URL)
     #[arg(long, env("DB_PATH"), default_value = "db/count.db")]
     db_path: String,
 
+    /// Database backend to use; inferred from `db_path`'s scheme when omitted
+    #[arg(long, env("DB_BACKEND"))]
+    db_backend: Option<String>,
+
     /// Path to assets directory
     #[arg(long, env("ASSETS_PATH"), default_value = "assets/theme")]
     assets_path: String,
@@ -38,148 +54,80 @@ struct Args {
     /// Authentication token for setting counts
     #[arg(long, env("API_AUTH_TOKEN"))]
     api_auth_token: Option<String>,
-}
 
-struct AppState {
-    db: SqlitePool,
-    themes: Arc<ThemeMap>,
-    default_theme: String,
-    api_auth_token: Option<String>,
-}
+    /// Root secret used to mint and verify macaroon auth tokens
+    #[arg(long, env("MACAROON_SECRET"))]
+    macaroon_secret: Option<String>,
 
-type DigitMap = HashMap<char, String>;
-type ThemeMap = HashMap<Arc<str>, ThemeData>;
+    /// PEM certificate chain for native TLS termination; requires --tls-key
+    #[arg(long, env("TLS_CERT"), requires = "tls_key")]
+    tls_cert: Option<String>,
 
-struct ThemeData {
-    id_to_uri: HashMap<String, Arc<str>>,
-    digits: DigitMap,
-}
+    /// PEM private key for native TLS termination; requires --tls-cert
+    #[arg(long, env("TLS_KEY"), requires = "tls_cert")]
+    tls_key: Option<String>,
 
-async fn init_db(path: &str) -> SqlitePool {
-    info!("initializing database at {}", path);
-
-    if let Some(parent) = Path::new(path).parent() {
-        std::fs::create_dir_all(parent).expect("create db dir");
-    }
-
-    std::fs::OpenOptions::new()
-        .create(true)
-        .write(true)
-        .truncate(false)
-        .open(path)
-        .expect("create db file");
-
-    let pool = SqlitePool::connect(&format!("sqlite://{path}"))
-        .await
-        .expect("connect db");
-
-    sqlx::query(
-        "CREATE TABLE IF NOT EXISTS tb_count (
-            id   INTEGER PRIMARY KEY AUTOINCREMENT,
-            name TEXT UNIQUE NOT NULL,
-            num  INTEGER NOT NULL DEFAULT 0
-        )",
-    )
-    .execute(&pool)
-    .await
-    .expect("create table");
-
-    info!("database initialized");
-    pool
+    #[command(subcommand)]
+    command: Option<Command>,
 }
 
-async fn increment(pool: &SqlitePool, name: &str) -> i64 {
-    debug!("incrementing counter for {}", name);
-
-    sqlx::query(
-        "INSERT INTO tb_count (name, num) VALUES (?1, 1)
-         ON CONFLICT(name) DO UPDATE SET num = num + 1",
-    )
-    .bind(name)
-    .execute(pool)
-    .await
-    .ok();
-
-    let count = sqlx::query_scalar::<_, i64>("SELECT num FROM tb_count WHERE name = ?1")
-        .bind(name)
-        .fetch_one(pool)
-        .await
-        .unwrap_or(0);
-
-    debug!("counter {} now at {}", name, count);
-    count
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Dump all counters to a JSON file
+    Dump {
+        /// File to write the JSON dump to
+        #[arg(long)]
+        out: String,
+    },
+    /// Import counters from a JSON file, upserting each one
+    Import {
+        /// JSON file produced by `dump`
+        path: String,
+    },
+    /// Create a new API key and print its plaintext token once
+    CreateKey {
+        /// Human-readable description of what the key is for
+        #[arg(long)]
+        description: String,
+        /// Comma-separated permissions: SET, RESET, DELETE
+        #[arg(long)]
+        permissions: String,
+        /// Optional RFC3339 expiry, e.g. 2025-01-01T00:00:00Z
+        #[arg(long)]
+        expires_at: Option<String>,
+    },
+    /// Mint a macaroon scoped to one counter, optionally with an expiry, and print it once
+    MintMacaroon {
+        /// Counter name the token is allowed to set
+        #[arg(long)]
+        name: String,
+        /// Optional RFC3339 expiry, e.g. 2025-01-01T00:00:00Z
+        #[arg(long)]
+        expires_at: Option<String>,
+    },
 }
 
-fn mime_for(ext: &str) -> &'static str {
-    match ext.to_ascii_lowercase().as_str() {
-        "png" => "image/png",
-        "gif" => "image/gif",
-        "jpg" | "jpeg" => "image/jpeg",
-        _ => "application/octet-stream",
-    }
-}
-
-fn load_themes(dir: &str) -> ThemeMap {
-    info!("loading themes from {}", dir);
-    let mut themes = ThemeMap::new();
-    let Ok(entries) = std::fs::read_dir(dir) else {
-        warn!("failed to read themes directory");
-        return themes;
-    };
-
-    for entry in entries.flatten() {
-        if !entry.path().is_dir() {
-            continue;
-        }
-
-        let theme_name: Arc<str> = entry.file_name().to_string_lossy().into();
-        let mut uri_to_id: HashMap<Arc<str>, String> = HashMap::new();
-        let mut digits = DigitMap::new();
-        let mut id_counter = 0;
-        debug!("loading theme: {}", theme_name);
-
-        let Ok(files) = std::fs::read_dir(entry.path()) else {
-            warn!("failed to read theme directory: {}", theme_name);
-            continue;
-        };
-
-        for file in files.flatten() {
-            let path = file.path();
-            if let Some((digit, uri)) = load_digit(&path) {
-                let id = uri_to_id.entry(uri.clone()).or_insert_with(|| {
-                    let new_id = format!("i{}", id_counter);
-                    id_counter += 1;
-                    new_id
-                });
-                digits.insert(digit, id.clone());
-            }
-        }
-
-        if !digits.is_empty() {
-            let id_to_uri: HashMap<String, Arc<str>> =
-                uri_to_id.into_iter().map(|(uri, id)| (id, uri)).collect();
-            info!("loaded theme: {} with {} digits", theme_name, digits.len());
-            themes.insert(theme_name, ThemeData { id_to_uri, digits });
-        }
-    }
-
-    info!("loaded {} themes total", themes.len());
-    themes
+struct AppState {
+    db: Arc<dyn Store>,
+    themes: Arc<ArcSwap<ThemeMap>>,
+    default_theme: String,
+    api_auth_token: Option<String>,
+    macaroon_secret: Option<Vec<u8>>,
+    raster_cache: RasterCache,
 }
 
-fn load_digit(path: &Path) -> Option<(char, Arc<str>)> {
-    let stem = path.file_stem()?.to_str()?;
-    let digit = stem.chars().next()?;
-    if !digit.is_ascii_digit() {
-        return None;
+/// Connects to the configured database, resolving the backend from `--db-backend` or,
+/// failing that, from the scheme of `db_path` (defaulting to SQLite for a bare path).
+async fn connect_store(db_path: &str, db_backend: Option<&str>) -> Arc<dyn Store> {
+    let backend = db_backend
+        .map(|b| DbBackend::parse(b).unwrap_or_else(|| panic!("unknown db backend: {b}")))
+        .or_else(|| DbBackend::from_url(db_path))
+        .unwrap_or(DbBackend::Sqlite);
+
+    match backend {
+        DbBackend::Sqlite => Arc::new(SqliteStore::connect(db_path).await),
+        DbBackend::Postgres => Arc::new(PostgresStore::connect(db_path).await),
     }
-
-    let bytes = std::fs::read(path).ok()?;
-    let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
-    let uri = format!("data:{};base64,{}", mime_for(ext), BASE64.encode(&bytes));
-
-    debug!("loaded digit {} ({})", digit, path.display());
-    Some((digit, uri.into()))
 }
 
 fn render_svg(theme_data: &ThemeData, count: i64) -> String {
@@ -222,10 +170,13 @@ fn render_svg(theme_data: &ThemeData, count: i64) -> String {
 struct GetQuery {
     #[serde(default)]
     theme: Option<String>,
+    #[serde(default)]
+    format: Option<String>,
 }
 
 #[get("/get/{name}")]
 async fn get_image(
+    req: HttpRequest,
     path: web::Path<String>,
     query: web::Query<GetQuery>,
     state: web::Data<AppState>,
@@ -233,24 +184,65 @@ async fn get_image(
     let name = path.into_inner();
     debug!("GET /get/{}?theme={:?}", name, query.theme);
 
-    let count = increment(&state.db, &name).await;
+    let count = match state.db.increment(&name).await {
+        Ok(count) => count,
+        Err(e) => {
+            error!("failed to increment counter {}: {}", name, e);
+            return HttpResponse::InternalServerError().body("storage error");
+        }
+    };
 
     let theme_key = query.theme.as_deref().unwrap_or(&state.default_theme);
-    let theme_data = state
-        .themes
+    let themes = state.themes.load();
+    let theme_data = themes
         .get(theme_key)
-        .or_else(|| state.themes.get(&*state.default_theme))
-        .or_else(|| state.themes.values().next());
+        .or_else(|| themes.get(&*state.default_theme))
+        .or_else(|| themes.values().next());
 
     let Some(td) = theme_data else {
         error!("no themes available");
         return HttpResponse::InternalServerError().body("no themes");
     };
 
-    HttpResponse::Ok()
-        .content_type("image/svg+xml")
-        .insert_header(("Cache-Control", "no-cache, no-store, must-revalidate"))
-        .body(render_svg(td, count))
+    let accept = req.headers().get("Accept").and_then(|v| v.to_str().ok());
+    let format = negotiate_format(query.format.as_deref(), accept);
+    let svg = render_svg(td, count);
+
+    if format == OutputFormat::Svg {
+        return HttpResponse::Ok()
+            .content_type(format.content_type())
+            .insert_header(("Cache-Control", "no-cache, no-store, must-revalidate"))
+            .body(svg);
+    }
+
+    if let Some(bytes) = state.raster_cache.get(theme_key, count, format) {
+        debug!("raster cache hit for {}/{}/{:?}", theme_key, count, format);
+        return HttpResponse::Ok()
+            .content_type(format.content_type())
+            .insert_header(("Cache-Control", "no-cache, no-store, must-revalidate"))
+            .body(bytes.to_vec());
+    }
+
+    let width = PAD_LENGTH as u32 * IMG_WIDTH;
+    match rasterize(&svg, width, IMG_HEIGHT, format) {
+        Some(bytes) => {
+            let bytes: Arc<[u8]> = bytes.into();
+            state
+                .raster_cache
+                .insert(theme_key, count, format, bytes.clone());
+            HttpResponse::Ok()
+                .content_type(format.content_type())
+                .insert_header(("Cache-Control", "no-cache, no-store, must-revalidate"))
+                .body(bytes.to_vec())
+        }
+        None => {
+            warn!("rasterization to {:?} failed, falling back to svg", format);
+            HttpResponse::Ok()
+                .content_type(OutputFormat::Svg.content_type())
+                .insert_header(("Cache-Control", "no-cache, no-store, must-revalidate"))
+                .body(svg)
+        }
+    }
 }
 
 #[derive(serde::Deserialize)]
@@ -258,6 +250,41 @@ struct SetQuery {
     count: i64,
 }
 
+/// Authorizes a `set` request for counter `name`, preferring a macaroon token (scoped and
+/// expiring) when a root secret is configured, falling back to the static auth token.
+fn authorize_set(req: &HttpRequest, state: &AppState, name: &str) -> Result<(), HttpResponse> {
+    if req.extensions().get::<ApiKeyAuthorized>().is_some() {
+        return Ok(());
+    }
+
+    let token = req
+        .headers()
+        .get("X-Auth-Token")
+        .and_then(|v| v.to_str().ok())
+        .ok_or_else(|| HttpResponse::Unauthorized().body("Missing token"))?;
+
+    if let Some(secret) = &state.macaroon_secret {
+        let macaroon = Macaroon::decode(token)
+            .ok_or_else(|| HttpResponse::Unauthorized().body("Malformed token"))?;
+        let ctx = VerifyContext {
+            name,
+            now: chrono::Utc::now(),
+        };
+        return macaroon
+            .verify(secret, &ctx)
+            .map_err(|e| {
+                warn!("macaroon verification failed for {}: {}", name, e);
+                HttpResponse::Unauthorized().body("Invalid token")
+            });
+    }
+
+    match &state.api_auth_token {
+        Some(expected) if token == expected => Ok(()),
+        Some(_) => Err(HttpResponse::Unauthorized().body("Invalid token")),
+        None => Err(HttpResponse::Unauthorized().body("Missing token configuration")),
+    }
+}
+
 #[get("/api/set/{name}")]
 async fn set_count(
     req: HttpRequest,
@@ -268,22 +295,8 @@ async fn set_count(
     let name = path.into_inner();
     let count = query.count;
 
-    let expected_token = match &state.api_auth_token {
-        Some(t) => t,
-        None => return HttpResponse::Unauthorized().body("Missing token configuration"),
-    };
-
-    let token = match req
-        .headers()
-        .get("X-Auth-Token")
-        .and_then(|v| v.to_str().ok())
-    {
-        Some(t) => t,
-        None => return HttpResponse::Unauthorized().body("Missing token"),
-    };
-
-    if token != expected_token {
-        return HttpResponse::Unauthorized().body("Invalid token");
+    if let Err(resp) = authorize_set(&req, &state, &name) {
+        return resp;
     }
 
     debug!("SET /api/set/{} count={}", name, count);
@@ -293,15 +306,10 @@ async fn set_count(
         return HttpResponse::BadRequest().body("count must be non-negative");
     }
 
-    sqlx::query(
-        "INSERT INTO tb_count (name, num) VALUES (?1, ?2)
-         ON CONFLICT(name) DO UPDATE SET num = ?2",
-    )
-    .bind(&name)
-    .bind(count)
-    .execute(&state.db)
-    .await
-    .ok();
+    if let Err(e) = state.db.set(&name, count).await {
+        error!("failed to set counter {}: {}", name, e);
+        return HttpResponse::InternalServerError().body("storage error");
+    }
 
     info!("set {} to {}", name, count);
     HttpResponse::Ok().body(format!("set count of '{}' to {}", name, count))
@@ -314,14 +322,71 @@ async fn main() -> std::io::Result<()> {
         .init();
 
     let args = Args::parse();
+
+    if let Some(command) = args.command {
+        return match command {
+            Command::Dump { out } => {
+                let db = connect_store(&args.db_path, args.db_backend.as_deref()).await;
+                info!("dumping counters to {}", out);
+                backup::dump(&*db, &out).await
+            }
+            Command::Import { path } => {
+                let db = connect_store(&args.db_path, args.db_backend.as_deref()).await;
+                info!("importing counters from {}", path);
+                backup::import(&*db, &path).await
+            }
+            Command::CreateKey {
+                description,
+                permissions,
+                expires_at,
+            } => {
+                let db = connect_store(&args.db_path, args.db_backend.as_deref()).await;
+                let permissions = Permissions::parse_list(&permissions).unwrap_or_else(|| {
+                    panic!("invalid --permissions '{permissions}', expected a comma-separated list of SET, RESET, DELETE")
+                });
+                let expires_at = expires_at
+                    .map(|s| {
+                        chrono::DateTime::parse_from_rfc3339(&s)
+                            .unwrap_or_else(|e| panic!("invalid --expires-at '{s}': {e}"))
+                            .with_timezone(&chrono::Utc)
+                    });
+
+                let token = db
+                    .create_key(&description, permissions, expires_at)
+                    .await
+                    .map_err(std::io::Error::other)?;
+
+                println!("created key: {token}");
+                println!("store it now, it cannot be retrieved again");
+                Ok(())
+            }
+            Command::MintMacaroon { name, expires_at } => {
+                let secret = args.macaroon_secret.as_deref().unwrap_or_else(|| {
+                    panic!("--macaroon-secret (or MACAROON_SECRET) is required to mint a macaroon")
+                });
+
+                let mut caveats = vec![format!("name = {name}")];
+                if let Some(expires_at) = &expires_at {
+                    caveats.push(format!("expires < {expires_at}"));
+                }
+
+                let macaroon = Macaroon::mint(secret.as_bytes(), &name, caveats);
+                println!("{}", macaroon.encode());
+                println!("store it now, it cannot be retrieved again");
+                Ok(())
+            }
+        };
+    }
+
     info!("starting yamcrs server");
     info!("bind: {}:{}", args.host, args.port);
     info!("db: {}", args.db_path);
     info!("assets: {}", args.assets_path);
     info!("default theme: {}", args.default_theme);
 
-    let db = init_db(&args.db_path).await;
-    let themes = Arc::new(load_themes(&args.assets_path));
+    let db = connect_store(&args.db_path, args.db_backend.as_deref()).await;
+    let themes = Arc::new(ArcSwap::from_pointee(load_themes(&args.assets_path)));
+    spawn_theme_watcher(args.assets_path.clone(), themes.clone());
 
     let host = args.host.clone();
     let port = args.port;
@@ -331,17 +396,33 @@ async fn main() -> std::io::Result<()> {
         themes,
         default_theme: args.default_theme,
         api_auth_token: args.api_auth_token,
+        macaroon_secret: args.macaroon_secret.map(String::into_bytes),
+        raster_cache: RasterCache::default(),
     });
 
-    info!("listening on http://{}:{}", host, port);
-
-    HttpServer::new(move || {
+    let server = HttpServer::new(move || {
         App::new()
             .app_data(state.clone())
             .service(get_image)
-            .service(set_count)
-    })
-    .bind((host.clone(), port))?
-    .run()
-    .await
+            .service(
+                web::scope("")
+                    .wrap(ApiKeyAuth::new(Permissions::SET))
+                    .service(set_count),
+            )
+    });
+
+    match (&args.tls_cert, &args.tls_key) {
+        (Some(cert), Some(key)) => {
+            info!("listening on https://{}:{} (native TLS)", host, port);
+            let tls_config = tls::load_config(cert, key)?;
+            server
+                .bind_rustls_0_23((host.clone(), port), tls_config)?
+                .run()
+                .await
+        }
+        _ => {
+            info!("listening on http://{}:{}", host, port);
+            server.bind((host.clone(), port))?.run().await
+        }
+    }
 }
@@ -0,0 +1,170 @@
+use std::collections::{HashMap, VecDeque};
+use std::io::Cursor;
+use std::sync::{Arc, RwLock};
+
+use image::{DynamicImage, ImageFormat, RgbaImage};
+use tracing::{debug, warn};
+
+/// Maximum number of encoded images `RasterCache` keeps before evicting the oldest entry.
+/// Bounds memory since counters are monotonically increasing, so most keys are only ever
+/// requested once.
+const MAX_CACHE_ENTRIES: usize = 256;
+
+/// Rasterized output formats `get_image` can emit alongside the default SVG.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OutputFormat {
+    Svg,
+    Png,
+    Webp,
+    Gif,
+}
+
+impl OutputFormat {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Self::Svg => "image/svg+xml",
+            Self::Png => "image/png",
+            Self::Webp => "image/webp",
+            Self::Gif => "image/gif",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "svg" => Some(Self::Svg),
+            "png" => Some(Self::Png),
+            "webp" => Some(Self::Webp),
+            "gif" => Some(Self::Gif),
+            _ => None,
+        }
+    }
+
+    fn from_mime(mime: &str) -> Option<Self> {
+        match mime.trim() {
+            "image/svg+xml" => Some(Self::Svg),
+            "image/png" => Some(Self::Png),
+            "image/webp" => Some(Self::Webp),
+            "image/gif" => Some(Self::Gif),
+            _ => None,
+        }
+    }
+}
+
+/// Picks the response format from an explicit `?format=` query param, falling back to
+/// `Accept` header negotiation, and finally SVG when nothing recognizable was requested.
+pub fn negotiate_format(query_format: Option<&str>, accept: Option<&str>) -> OutputFormat {
+    if let Some(name) = query_format {
+        if let Some(fmt) = OutputFormat::from_name(name) {
+            return fmt;
+        }
+        warn!("unknown format '{}' requested, falling back to svg", name);
+        return OutputFormat::Svg;
+    }
+
+    if let Some(accept) = accept {
+        for mime in accept.split(',') {
+            let mime = mime.split(';').next().unwrap_or("").trim();
+            if let Some(fmt) = OutputFormat::from_mime(mime) {
+                return fmt;
+            }
+        }
+    }
+
+    OutputFormat::Svg
+}
+
+/// Rasterizes `svg` to an RGBA buffer of `width`x`height` and encodes it as `format`.
+/// Returns `None` if `format` is `Svg` (callers should serve the SVG string directly) or
+/// if rasterization/encoding fails.
+pub fn rasterize(svg: &str, width: u32, height: u32, format: OutputFormat) -> Option<Vec<u8>> {
+    if format == OutputFormat::Svg {
+        return None;
+    }
+
+    let opt = usvg::Options::default();
+    let tree = usvg::Tree::from_str(svg, &opt).ok()?;
+
+    let mut pixmap = tiny_skia::Pixmap::new(width, height)?;
+    resvg::render(&tree, tiny_skia::Transform::identity(), &mut pixmap.as_mut());
+
+    let mut buf = pixmap.take();
+    unpremultiply(&mut buf);
+    let image: RgbaImage = RgbaImage::from_raw(width, height, buf)?;
+
+    match format {
+        OutputFormat::Svg => unreachable!(),
+        OutputFormat::Png | OutputFormat::Gif => {
+            let image_format = if format == OutputFormat::Png {
+                ImageFormat::Png
+            } else {
+                ImageFormat::Gif
+            };
+            let mut buf = Cursor::new(Vec::new());
+            DynamicImage::ImageRgba8(image)
+                .write_to(&mut buf, image_format)
+                .ok()?;
+            Some(buf.into_inner())
+        }
+        OutputFormat::Webp => {
+            let encoder = webp::Encoder::from_rgba(image.as_raw(), width, height);
+            Some(encoder.encode(90.0).to_vec())
+        }
+    }
+}
+
+/// `tiny_skia::Pixmap` buffers are premultiplied RGBA8, but the `image`/`webp` encoders
+/// both expect straight alpha. Without this, antialiased glyph edges come out darker/shifted
+/// compared to the source SVG. Converts `buf` (a tightly packed RGBA8 buffer) in place.
+fn unpremultiply(buf: &mut [u8]) {
+    for pixel in buf.chunks_exact_mut(4) {
+        let a = pixel[3];
+        if a == 0 || a == 255 {
+            continue;
+        }
+        for channel in &mut pixel[..3] {
+            *channel = (*channel as u32 * 255 / a as u32).min(255) as u8;
+        }
+    }
+}
+
+type CacheKey = (String, i64, OutputFormat);
+
+/// Caches encoded raster bytes keyed by `(theme, count, format)`.
+///
+/// `count` comes from a strictly increasing counter, so the same key is essentially never
+/// requested twice in practice; this exists to absorb bursts of repeat requests for the same
+/// rendered value (e.g. several clients polling the same badge between increments) rather
+/// than to serve long-term hit rates. Bounded to `MAX_CACHE_ENTRIES` with FIFO eviction so it
+/// can't grow without limit over the life of the process.
+#[derive(Default)]
+pub struct RasterCache {
+    entries: RwLock<HashMap<CacheKey, Arc<[u8]>>>,
+    order: RwLock<VecDeque<CacheKey>>,
+}
+
+impl RasterCache {
+    pub fn get(&self, theme: &str, count: i64, format: OutputFormat) -> Option<Arc<[u8]>> {
+        let key = (theme.to_string(), count, format);
+        self.entries.read().ok()?.get(&key).cloned()
+    }
+
+    pub fn insert(&self, theme: &str, count: i64, format: OutputFormat, bytes: Arc<[u8]>) {
+        let key = (theme.to_string(), count, format);
+        let (Ok(mut entries), Ok(mut order)) = (self.entries.write(), self.order.write()) else {
+            return;
+        };
+
+        debug!("caching rasterized image for {:?}", key);
+        if entries.insert(key.clone(), bytes).is_none() {
+            order.push_back(key);
+        }
+
+        while entries.len() > MAX_CACHE_ENTRIES {
+            if let Some(oldest) = order.pop_front() {
+                entries.remove(&oldest);
+            } else {
+                break;
+            }
+        }
+    }
+}
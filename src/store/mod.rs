@@ -0,0 +1,156 @@
+mod postgres;
+mod sqlite;
+
+pub use postgres::PostgresStore;
+pub use sqlite::SqliteStore;
+
+use async_trait::async_trait;
+
+/// Backend selected via `--db-backend` or inferred from the connection URL scheme.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DbBackend {
+    Sqlite,
+    Postgres,
+}
+
+impl DbBackend {
+    /// Parses an explicit `--db-backend` / `DB_BACKEND` value.
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "sqlite" => Some(Self::Sqlite),
+            "postgres" | "postgresql" => Some(Self::Postgres),
+            _ => None,
+        }
+    }
+
+    /// Infers the backend from a connection URL's scheme, e.g. `postgres://...` or `sqlite://...`.
+    pub fn from_url(url: &str) -> Option<Self> {
+        if url.starts_with("postgres://") || url.starts_with("postgresql://") {
+            Some(Self::Postgres)
+        } else if url.starts_with("sqlite://") {
+            Some(Self::Sqlite)
+        } else {
+            None
+        }
+    }
+}
+
+/// A single `tb_count` row, as exchanged with the outside world by `dump`/`import`.
+#[derive(Debug, Clone, sqlx::FromRow, serde::Serialize, serde::Deserialize)]
+pub struct CounterRecord {
+    pub name: String,
+    pub num: i64,
+}
+
+/// Storage abstraction for the `tb_count` table, so handlers don't depend on a specific database.
+#[async_trait]
+pub trait CounterStore: Send + Sync {
+    async fn increment(&self, name: &str) -> Result<i64, sqlx::Error>;
+    async fn set(&self, name: &str, count: i64) -> Result<(), sqlx::Error>;
+    /// Reads the current count without incrementing it. No handler calls this yet, but it
+    /// rounds out the trait for future read-only endpoints and direct callers of the store.
+    #[allow(dead_code)]
+    async fn get(&self, name: &str) -> Result<i64, sqlx::Error>;
+    async fn list(&self) -> Result<Vec<CounterRecord>, sqlx::Error>;
+}
+
+/// Bitset of actions an API key may perform. Persisted as a plain integer in `api_keys`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Permissions(i64);
+
+impl Permissions {
+    pub const SET: Self = Self(1 << 0);
+    pub const RESET: Self = Self(1 << 1);
+    pub const DELETE: Self = Self(1 << 2);
+
+    pub fn empty() -> Self {
+        Self(0)
+    }
+
+    pub fn union(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn bits(self) -> i64 {
+        self.0
+    }
+
+    pub fn from_bits(bits: i64) -> Self {
+        Self(bits)
+    }
+
+    /// Parses a comma-separated list like `SET,DELETE` as used by `create-key --permissions`.
+    pub fn parse_list(s: &str) -> Option<Self> {
+        s.split(',').try_fold(Self::empty(), |acc, part| {
+            let perm = match part.trim().to_ascii_uppercase().as_str() {
+                "SET" => Self::SET,
+                "RESET" => Self::RESET,
+                "DELETE" => Self::DELETE,
+                _ => return None,
+            };
+            Some(acc.union(perm))
+        })
+    }
+}
+
+/// An issued API key: its hash (never the plaintext), what it's allowed to do, and an
+/// optional expiry.
+#[derive(Debug, Clone)]
+pub struct ApiKeyRecord {
+    /// Not read anywhere yet, but persisted so a future `list-keys` command can show it.
+    #[allow(dead_code)]
+    pub description: String,
+    pub permissions: Permissions,
+    pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl ApiKeyRecord {
+    pub fn is_expired(&self) -> bool {
+        self.expires_at
+            .is_some_and(|expires_at| chrono::Utc::now() >= expires_at)
+    }
+}
+
+/// Storage abstraction for the `api_keys` table backing multi-key, permissioned auth.
+#[async_trait]
+pub trait ApiKeyStore: Send + Sync {
+    /// Creates a key with the given description and permissions, returning the plaintext
+    /// token. Only the key's hash is persisted; the plaintext is never stored or shown again.
+    async fn create_key(
+        &self,
+        description: &str,
+        permissions: Permissions,
+        expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    ) -> Result<String, sqlx::Error>;
+
+    /// Resolves a plaintext token (as presented in `X-Auth-Token`) to its key record.
+    async fn resolve_key(&self, token: &str) -> Result<Option<ApiKeyRecord>, sqlx::Error>;
+}
+
+/// A storage backend that supports both counters and API keys. Implemented automatically
+/// by any type implementing both, so `AppState` can hold a single trait object.
+pub trait Store: CounterStore + ApiKeyStore {}
+impl<T: CounterStore + ApiKeyStore + ?Sized> Store for T {}
+
+fn hex_encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn hash_token(token: &str) -> String {
+    use sha2::{Digest, Sha256};
+    hex_encode(&Sha256::digest(token.as_bytes()))
+}
+
+/// Generates a new random plaintext API key, prefixed so it's recognizable in logs/diffs.
+fn generate_token() -> String {
+    use base64::{Engine as _, engine::general_purpose::URL_SAFE_NO_PAD as BASE64};
+    use rand::RngCore;
+
+    let mut bytes = [0u8; 32];
+    rand::rngs::OsRng.fill_bytes(&mut bytes);
+    format!("ymc_{}", BASE64.encode(bytes))
+}
@@ -0,0 +1,140 @@
+use async_trait::async_trait;
+use sqlx::PgPool;
+use tracing::{debug, info};
+
+use super::{ApiKeyRecord, ApiKeyStore, CounterRecord, CounterStore, Permissions};
+
+pub struct PostgresStore {
+    pool: PgPool,
+}
+
+impl PostgresStore {
+    /// Connects to Postgres at `url` and ensures the `tb_count` table exists.
+    pub async fn connect(url: &str) -> Self {
+        info!("initializing postgres database");
+
+        let pool = PgPool::connect(url).await.expect("connect db");
+
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS tb_count (
+                id   SERIAL PRIMARY KEY,
+                name TEXT UNIQUE NOT NULL,
+                num  BIGINT NOT NULL DEFAULT 0
+            )",
+        )
+        .execute(&pool)
+        .await
+        .expect("create table");
+
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS api_keys (
+                id          SERIAL PRIMARY KEY,
+                key_hash    TEXT UNIQUE NOT NULL,
+                description TEXT NOT NULL,
+                permissions BIGINT NOT NULL,
+                expires_at  TIMESTAMPTZ
+            )",
+        )
+        .execute(&pool)
+        .await
+        .expect("create table");
+
+        info!("postgres database initialized");
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl CounterStore for PostgresStore {
+    async fn increment(&self, name: &str) -> Result<i64, sqlx::Error> {
+        debug!("incrementing counter for {}", name);
+
+        sqlx::query(
+            "INSERT INTO tb_count (name, num) VALUES ($1, 1)
+             ON CONFLICT(name) DO UPDATE SET num = tb_count.num + 1",
+        )
+        .bind(name)
+        .execute(&self.pool)
+        .await?;
+
+        let count = sqlx::query_scalar::<_, i64>("SELECT num FROM tb_count WHERE name = $1")
+            .bind(name)
+            .fetch_one(&self.pool)
+            .await?;
+
+        debug!("counter {} now at {}", name, count);
+        Ok(count)
+    }
+
+    async fn set(&self, name: &str, count: i64) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "INSERT INTO tb_count (name, num) VALUES ($1, $2)
+             ON CONFLICT(name) DO UPDATE SET num = $2",
+        )
+        .bind(name)
+        .bind(count)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get(&self, name: &str) -> Result<i64, sqlx::Error> {
+        let count = sqlx::query_scalar::<_, i64>("SELECT num FROM tb_count WHERE name = $1")
+            .bind(name)
+            .fetch_optional(&self.pool)
+            .await?
+            .unwrap_or(0);
+
+        Ok(count)
+    }
+
+    async fn list(&self) -> Result<Vec<CounterRecord>, sqlx::Error> {
+        sqlx::query_as::<_, CounterRecord>("SELECT name, num FROM tb_count ORDER BY name")
+            .fetch_all(&self.pool)
+            .await
+    }
+}
+
+#[async_trait]
+impl ApiKeyStore for PostgresStore {
+    async fn create_key(
+        &self,
+        description: &str,
+        permissions: Permissions,
+        expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    ) -> Result<String, sqlx::Error> {
+        let token = super::generate_token();
+        let key_hash = super::hash_token(&token);
+
+        sqlx::query(
+            "INSERT INTO api_keys (key_hash, description, permissions, expires_at)
+             VALUES ($1, $2, $3, $4)",
+        )
+        .bind(&key_hash)
+        .bind(description)
+        .bind(permissions.bits())
+        .bind(expires_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(token)
+    }
+
+    async fn resolve_key(&self, token: &str) -> Result<Option<ApiKeyRecord>, sqlx::Error> {
+        let key_hash = super::hash_token(token);
+
+        let row = sqlx::query_as::<_, (String, i64, Option<chrono::DateTime<chrono::Utc>>)>(
+            "SELECT description, permissions, expires_at FROM api_keys WHERE key_hash = $1",
+        )
+        .bind(&key_hash)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(|(description, permissions, expires_at)| ApiKeyRecord {
+            description,
+            permissions: Permissions::from_bits(permissions),
+            expires_at,
+        }))
+    }
+}
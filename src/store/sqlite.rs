@@ -0,0 +1,159 @@
+use std::path::Path;
+
+use async_trait::async_trait;
+use sqlx::SqlitePool;
+use tracing::{debug, info};
+
+use super::{ApiKeyRecord, ApiKeyStore, CounterRecord, CounterStore, Permissions};
+
+pub struct SqliteStore {
+    pool: SqlitePool,
+}
+
+impl SqliteStore {
+    /// Connects to a SQLite database at `path`, creating the file and schema if needed.
+    /// `path` may be a bare filesystem path or a `sqlite://` URL.
+    pub async fn connect(path: &str) -> Self {
+        let path = path.strip_prefix("sqlite://").unwrap_or(path);
+        info!("initializing sqlite database at {}", path);
+
+        if let Some(parent) = Path::new(path).parent() {
+            std::fs::create_dir_all(parent).expect("create db dir");
+        }
+
+        std::fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(false)
+            .open(path)
+            .expect("create db file");
+
+        let pool = SqlitePool::connect(&format!("sqlite://{path}"))
+            .await
+            .expect("connect db");
+
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS tb_count (
+                id   INTEGER PRIMARY KEY AUTOINCREMENT,
+                name TEXT UNIQUE NOT NULL,
+                num  INTEGER NOT NULL DEFAULT 0
+            )",
+        )
+        .execute(&pool)
+        .await
+        .expect("create table");
+
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS api_keys (
+                id          INTEGER PRIMARY KEY AUTOINCREMENT,
+                key_hash    TEXT UNIQUE NOT NULL,
+                description TEXT NOT NULL,
+                permissions INTEGER NOT NULL,
+                expires_at  TEXT
+            )",
+        )
+        .execute(&pool)
+        .await
+        .expect("create table");
+
+        info!("sqlite database initialized");
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl CounterStore for SqliteStore {
+    async fn increment(&self, name: &str) -> Result<i64, sqlx::Error> {
+        debug!("incrementing counter for {}", name);
+
+        sqlx::query(
+            "INSERT INTO tb_count (name, num) VALUES (?1, 1)
+             ON CONFLICT(name) DO UPDATE SET num = num + 1",
+        )
+        .bind(name)
+        .execute(&self.pool)
+        .await?;
+
+        let count = sqlx::query_scalar::<_, i64>("SELECT num FROM tb_count WHERE name = ?1")
+            .bind(name)
+            .fetch_one(&self.pool)
+            .await?;
+
+        debug!("counter {} now at {}", name, count);
+        Ok(count)
+    }
+
+    async fn set(&self, name: &str, count: i64) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "INSERT INTO tb_count (name, num) VALUES (?1, ?2)
+             ON CONFLICT(name) DO UPDATE SET num = ?2",
+        )
+        .bind(name)
+        .bind(count)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get(&self, name: &str) -> Result<i64, sqlx::Error> {
+        let count = sqlx::query_scalar::<_, i64>("SELECT num FROM tb_count WHERE name = ?1")
+            .bind(name)
+            .fetch_optional(&self.pool)
+            .await?
+            .unwrap_or(0);
+
+        Ok(count)
+    }
+
+    async fn list(&self) -> Result<Vec<CounterRecord>, sqlx::Error> {
+        sqlx::query_as::<_, CounterRecord>("SELECT name, num FROM tb_count ORDER BY name")
+            .fetch_all(&self.pool)
+            .await
+    }
+}
+
+#[async_trait]
+impl ApiKeyStore for SqliteStore {
+    async fn create_key(
+        &self,
+        description: &str,
+        permissions: Permissions,
+        expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    ) -> Result<String, sqlx::Error> {
+        let token = super::generate_token();
+        let key_hash = super::hash_token(&token);
+
+        sqlx::query(
+            "INSERT INTO api_keys (key_hash, description, permissions, expires_at)
+             VALUES (?1, ?2, ?3, ?4)",
+        )
+        .bind(&key_hash)
+        .bind(description)
+        .bind(permissions.bits())
+        .bind(expires_at.map(|t| t.to_rfc3339()))
+        .execute(&self.pool)
+        .await?;
+
+        Ok(token)
+    }
+
+    async fn resolve_key(&self, token: &str) -> Result<Option<ApiKeyRecord>, sqlx::Error> {
+        let key_hash = super::hash_token(token);
+
+        let row = sqlx::query_as::<_, (String, i64, Option<String>)>(
+            "SELECT description, permissions, expires_at FROM api_keys WHERE key_hash = ?1",
+        )
+        .bind(&key_hash)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(|(description, permissions, expires_at)| ApiKeyRecord {
+            description,
+            permissions: Permissions::from_bits(permissions),
+            expires_at: expires_at
+                .and_then(|s| chrono::DateTime::parse_from_rfc3339(&s).ok())
+                .map(|dt| dt.with_timezone(&chrono::Utc)),
+        }))
+    }
+}
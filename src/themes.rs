@@ -0,0 +1,191 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+use arc_swap::ArcSwap;
+use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
+use notify::{RecursiveMode, Watcher};
+use rust_embed::RustEmbed;
+use tracing::{debug, info, warn};
+
+pub type DigitMap = HashMap<char, String>;
+pub type ThemeMap = HashMap<Arc<str>, ThemeData>;
+
+pub struct ThemeData {
+    pub id_to_uri: HashMap<String, Arc<str>>,
+    pub digits: DigitMap,
+}
+
+/// Default theme set compiled into the binary, used when `--assets-path` is absent or
+/// empty so a single binary is self-contained.
+#[derive(RustEmbed)]
+#[folder = "assets/theme"]
+struct DefaultThemes;
+
+fn mime_for(ext: &str) -> &'static str {
+    match ext.to_ascii_lowercase().as_str() {
+        "png" => "image/png",
+        "gif" => "image/gif",
+        "jpg" | "jpeg" => "image/jpeg",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Loads themes from `dir` on disk, falling back to the themes embedded in the binary
+/// when `dir` is empty or unreadable.
+pub fn load_themes(dir: &str) -> ThemeMap {
+    if dir.is_empty() {
+        return load_embedded_themes();
+    }
+
+    info!("loading themes from {}", dir);
+    let mut themes = ThemeMap::new();
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        warn!("failed to read themes directory, falling back to embedded themes");
+        return load_embedded_themes();
+    };
+
+    for entry in entries.flatten() {
+        if !entry.path().is_dir() {
+            continue;
+        }
+
+        let theme_name: Arc<str> = entry.file_name().to_string_lossy().into();
+        let mut uri_to_id: HashMap<Arc<str>, String> = HashMap::new();
+        let mut digits = DigitMap::new();
+        let mut id_counter = 0;
+        debug!("loading theme: {}", theme_name);
+
+        let Ok(files) = std::fs::read_dir(entry.path()) else {
+            warn!("failed to read theme directory: {}", theme_name);
+            continue;
+        };
+
+        for file in files.flatten() {
+            let path = file.path();
+            if let Some((digit, uri)) = load_digit(&path) {
+                let id = uri_to_id.entry(uri.clone()).or_insert_with(|| {
+                    let new_id = format!("i{}", id_counter);
+                    id_counter += 1;
+                    new_id
+                });
+                digits.insert(digit, id.clone());
+            }
+        }
+
+        if !digits.is_empty() {
+            let id_to_uri: HashMap<String, Arc<str>> =
+                uri_to_id.into_iter().map(|(uri, id)| (id, uri)).collect();
+            info!("loaded theme: {} with {} digits", theme_name, digits.len());
+            themes.insert(theme_name, ThemeData { id_to_uri, digits });
+        }
+    }
+
+    info!("loaded {} themes total", themes.len());
+    themes
+}
+
+fn load_embedded_themes() -> ThemeMap {
+    info!("loading embedded default themes");
+    let mut by_theme: HashMap<String, Vec<String>> = HashMap::new();
+    for path in DefaultThemes::iter() {
+        if let Some((theme, _)) = path.split_once('/') {
+            by_theme
+                .entry(theme.to_string())
+                .or_default()
+                .push(path.to_string());
+        }
+    }
+
+    let mut themes = ThemeMap::new();
+    for (theme_name, files) in by_theme {
+        let mut uri_to_id: HashMap<Arc<str>, String> = HashMap::new();
+        let mut digits = DigitMap::new();
+        let mut id_counter = 0;
+
+        for file in files {
+            let Some(embedded) = DefaultThemes::get(&file) else {
+                continue;
+            };
+            let Some((digit, uri)) = load_digit_bytes(Path::new(&file), &embedded.data) else {
+                continue;
+            };
+            let id = uri_to_id.entry(uri.clone()).or_insert_with(|| {
+                let new_id = format!("i{}", id_counter);
+                id_counter += 1;
+                new_id
+            });
+            digits.insert(digit, id.clone());
+        }
+
+        if !digits.is_empty() {
+            let id_to_uri: HashMap<String, Arc<str>> =
+                uri_to_id.into_iter().map(|(uri, id)| (id, uri)).collect();
+            info!(
+                "loaded embedded theme: {} with {} digits",
+                theme_name,
+                digits.len()
+            );
+            themes.insert(theme_name.into(), ThemeData { id_to_uri, digits });
+        }
+    }
+
+    info!("loaded {} embedded themes total", themes.len());
+    themes
+}
+
+fn load_digit(path: &Path) -> Option<(char, Arc<str>)> {
+    let bytes = std::fs::read(path).ok()?;
+    load_digit_bytes(path, &bytes)
+}
+
+fn load_digit_bytes(path: &Path, bytes: &[u8]) -> Option<(char, Arc<str>)> {
+    let stem = path.file_stem()?.to_str()?;
+    let digit = stem.chars().next()?;
+    if !digit.is_ascii_digit() {
+        return None;
+    }
+
+    let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+    let uri = format!("data:{};base64,{}", mime_for(ext), BASE64.encode(bytes));
+
+    debug!("loaded digit {} ({})", digit, path.display());
+    Some((digit, uri.into()))
+}
+
+/// Spawns a background watcher that reloads themes from `dir` and atomically swaps them
+/// into `themes` whenever a file under it changes. No-op when `dir` is empty, since
+/// embedded themes can't change at runtime.
+pub fn spawn_theme_watcher(dir: String, themes: Arc<ArcSwap<ThemeMap>>) {
+    if dir.is_empty() {
+        return;
+    }
+
+    std::thread::spawn(move || {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let mut watcher = match notify::recommended_watcher(tx) {
+            Ok(w) => w,
+            Err(e) => {
+                warn!("failed to start theme watcher: {}", e);
+                return;
+            }
+        };
+
+        if let Err(e) = watcher.watch(Path::new(&dir), RecursiveMode::Recursive) {
+            warn!("failed to watch themes directory {}: {}", dir, e);
+            return;
+        }
+
+        info!("watching {} for theme changes", dir);
+
+        for event in rx {
+            match event {
+                Ok(_) => {
+                    debug!("theme directory changed, reloading");
+                    themes.store(Arc::new(load_themes(&dir)));
+                }
+                Err(e) => warn!("theme watcher error: {}", e),
+            }
+        }
+    });
+}
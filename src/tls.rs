@@ -0,0 +1,26 @@
+use std::fs::File;
+use std::io::{self, BufReader};
+
+use rustls::ServerConfig;
+use rustls::pki_types::CertificateDer;
+
+/// Loads a rustls server config from a PEM certificate chain and private key, so yamcrs
+/// can terminate TLS directly instead of relying on a fronting reverse proxy.
+pub fn load_config(cert_path: &str, key_path: &str) -> io::Result<ServerConfig> {
+    let mut cert_reader = BufReader::new(File::open(cert_path)?);
+    let cert_chain: Vec<CertificateDer<'static>> = rustls_pemfile::certs(&mut cert_reader)
+        .collect::<Result<_, _>>()?;
+
+    let mut key_reader = BufReader::new(File::open(key_path)?);
+    let key = rustls_pemfile::private_key(&mut key_reader)?.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("no private key found in {key_path}"),
+        )
+    })?;
+
+    ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(cert_chain, key)
+        .map_err(io::Error::other)
+}